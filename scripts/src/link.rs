@@ -0,0 +1,637 @@
+//! Linking a vertex/fragment pair: the fragment stage doesn't always read
+//! every `@location` the vertex stage produces, so when a vertex shader is
+//! compiled alongside a specific fragment shader we prune the varyings the
+//! fragment never consumes -- the cross-stage equivalent of what naga's
+//! HLSL backend does with a `FragmentEntryPoint`. This avoids Metal/driver
+//! validation errors and mismatched-interface warnings when a vertex
+//! shader is reused across several fragment shaders that each ignore some
+//! of its outputs.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{Binding, EntryPoint, Expression, Handle, LocalVariable, Module, ShaderStage, Type, TypeInner};
+
+use crate::{emit, CliArgs};
+
+/// What a (possibly indirect) read ultimately resolves back to: the whole
+/// value of one entry-point argument, or one member of it.
+#[derive(Clone, Copy)]
+struct ArgRef {
+    arg_index: usize,
+    member_index: Option<usize>,
+}
+
+/// Find every `Statement::Store` targeting a bare local variable (not a
+/// sub-field of one), recursing into nested control flow, and group them by
+/// the local they target. Used to trace `var x = input.field;`-style copies
+/// back to the argument they came from.
+fn collect_local_stores(
+    body: &naga::Block,
+    function: &naga::Function,
+    out: &mut HashMap<Handle<LocalVariable>, Vec<Handle<Expression>>>,
+) {
+    for stmt in body.iter() {
+        match stmt {
+            naga::Statement::Store { pointer, value } => {
+                if let Expression::LocalVariable(local) = function.expressions[*pointer] {
+                    out.entry(local).or_default().push(*value);
+                }
+            }
+            naga::Statement::Block(inner) => collect_local_stores(inner, function, out),
+            naga::Statement::If { accept, reject, .. } => {
+                collect_local_stores(accept, function, out);
+                collect_local_stores(reject, function, out);
+            }
+            naga::Statement::Switch { cases, .. } => {
+                for case in cases {
+                    collect_local_stores(&case.body, function, out);
+                }
+            }
+            naga::Statement::Loop { body: inner, continuing, .. } => {
+                collect_local_stores(inner, function, out);
+                collect_local_stores(continuing, function, out);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Find every call-instruction argument expression, recursing into nested
+/// control flow. Used to detect a struct-typed fragment input forwarded
+/// whole into a helper function, which `resolve_argument_ref` alone can't
+/// see through since it doesn't inspect callee bodies.
+fn collect_call_arguments(body: &naga::Block, out: &mut Vec<Handle<Expression>>) {
+    for stmt in body.iter() {
+        match stmt {
+            naga::Statement::Call { arguments, .. } => out.extend(arguments.iter().copied()),
+            naga::Statement::Block(inner) => collect_call_arguments(inner, out),
+            naga::Statement::If { accept, reject, .. } => {
+                collect_call_arguments(accept, out);
+                collect_call_arguments(reject, out);
+            }
+            naga::Statement::Switch { cases, .. } => {
+                for case in cases {
+                    collect_call_arguments(&case.body, out);
+                }
+            }
+            naga::Statement::Loop { body: inner, continuing, .. } => {
+                collect_call_arguments(inner, out);
+                collect_call_arguments(continuing, out);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Trace `handle` back to the argument (and member, if any) it reads: the
+/// argument itself, one member access on it (`input.field`), or a local
+/// variable that was assigned exactly once from either
+/// (`var x = input.field;`). Returns `Ok(None)` when `handle` is none of
+/// these -- including an access past a member (`input.field.x`), whose
+/// base `input.field` is a separate expression that resolves on its own
+/// when the caller walks every expression. Returns `Err` rather than
+/// guessing when it can't be traced -- a local reassigned more than once, or
+/// a load through anything other than a local/global variable -- since
+/// guessing wrong here means silently pruning a vertex output the fragment
+/// actually reads.
+fn resolve_argument_ref(
+    function: &naga::Function,
+    handle: Handle<Expression>,
+    local_stores: &HashMap<Handle<LocalVariable>, Vec<Handle<Expression>>>,
+) -> Result<Option<ArgRef>, String> {
+    match function.expressions[handle] {
+        Expression::FunctionArgument(arg_index) => Ok(Some(ArgRef {
+            arg_index: arg_index as usize,
+            member_index: None,
+        })),
+        Expression::AccessIndex { base, index } => match resolve_argument_ref(function, base, local_stores)? {
+            Some(ArgRef {
+                arg_index,
+                member_index: None,
+            }) => Ok(Some(ArgRef {
+                arg_index,
+                member_index: Some(index as usize),
+            })),
+            _ => Ok(None), // indexing past a member has no `@location` of its own
+        },
+        Expression::Load { pointer } => match function.expressions[pointer] {
+            Expression::LocalVariable(local) => match local_stores.get(&local) {
+                None => Ok(None),
+                Some(values) if values.len() == 1 => resolve_argument_ref(function, values[0], local_stores),
+                Some(_) => Err(
+                    "fragment entry point assigns a local variable more than once; can't safely trace whether it reads an input location".to_string(),
+                ),
+            },
+            Expression::GlobalVariable(_) => Ok(None),
+            _ => Err(
+                "fragment entry point loads through an indirection this pass can't trace back to its input argument".to_string(),
+            ),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Walk the fragment entry point's input-struct argument(s) and collect the
+/// `@location`s actually read in its body (as opposed to merely declared).
+fn consumed_locations(fragment_ep: &EntryPoint, fragment_module: &Module) -> Result<HashSet<u32>, String> {
+    // Locations reachable directly from a bare (non-struct) argument, and
+    // locations reachable by indexing into a struct argument's members.
+    let mut direct = HashMap::new();
+    let mut via_member = HashMap::new();
+
+    for (arg_index, arg) in fragment_ep.function.arguments.iter().enumerate() {
+        if let Some(Binding::Location { location, .. }) = arg.binding {
+            direct.insert(arg_index, location);
+            continue;
+        }
+        if let TypeInner::Struct { ref members, .. } = fragment_module.types[arg.ty].inner {
+            for (member_index, member) in members.iter().enumerate() {
+                if let Some(Binding::Location { location, .. }) = member.binding {
+                    via_member.insert((arg_index, member_index), location);
+                }
+            }
+        }
+    }
+
+    let mut local_stores = HashMap::new();
+    collect_local_stores(&fragment_ep.function.body, &fragment_ep.function, &mut local_stores);
+
+    let mut consumed = HashSet::new();
+    for (handle, _) in fragment_ep.function.expressions.iter() {
+        match resolve_argument_ref(&fragment_ep.function, handle, &local_stores)? {
+            Some(ArgRef {
+                arg_index,
+                member_index: None,
+            }) => {
+                if let Some(&location) = direct.get(&arg_index) {
+                    consumed.insert(location);
+                }
+            }
+            Some(ArgRef {
+                arg_index,
+                member_index: Some(member_index),
+            }) => {
+                if let Some(&location) = via_member.get(&(arg_index, member_index)) {
+                    consumed.insert(location);
+                }
+            }
+            None => {}
+        }
+    }
+
+    // A struct-typed argument forwarded whole into a helper function call
+    // can have any of its members read inside that callee, which we don't
+    // inspect -- so treat every declared member location of such an
+    // argument as consumed rather than missing it.
+    let mut call_arguments = Vec::new();
+    collect_call_arguments(&fragment_ep.function.body, &mut call_arguments);
+    for handle in call_arguments {
+        if let Some(ArgRef {
+            arg_index,
+            member_index: None,
+        }) = resolve_argument_ref(&fragment_ep.function, handle, &local_stores)?
+        {
+            for (&(other_arg_index, _), &location) in via_member.iter() {
+                if other_arg_index == arg_index {
+                    consumed.insert(location);
+                }
+            }
+        }
+    }
+
+    Ok(consumed)
+}
+
+/// Remove output-struct members whose `@location` isn't in `consumed`,
+/// keeping the original `@location` values on the ones that remain --
+/// matching naga's own HLSL `FragmentEntryPoint` precedent of dropping
+/// unused struct members without renumbering survivors, since the paired
+/// fragment module (read-only here) still expects its inputs at their
+/// original locations. Only supports the common case where the entry
+/// point's body ends in `return <output struct>`, traced through a literal
+/// `return VOut(...)`, a `var out = VOut(...); return out;` copy, or a
+/// `var out: VOut; out.a = ...; return out;` field-by-field build; a return
+/// value that can't be traced this way is rejected with an error (so the
+/// link fails rather than producing a broken module).
+fn prune_vertex_outputs(module: &mut Module, vertex_ep_index: usize, consumed: &HashSet<u32>) -> Result<(), String> {
+    let result_ty = module.entry_points[vertex_ep_index]
+        .function
+        .result
+        .as_ref()
+        .ok_or("vertex entry point has no return value")?
+        .ty;
+
+    let members = match module.types[result_ty].inner {
+        TypeInner::Struct { ref members, .. } => members.clone(),
+        _ => return Err("vertex entry point does not return a struct".to_string()),
+    };
+    let original_member_count = members.len();
+
+    let mut kept_indices = Vec::new();
+    let mut new_members = Vec::new();
+    for (index, member) in members.into_iter().enumerate() {
+        let keep = match member.binding {
+            Some(Binding::Location { location, .. }) => consumed.contains(&location),
+            _ => true, // builtins (e.g. clip position) always survive
+        };
+        if !keep {
+            continue;
+        }
+        kept_indices.push(index);
+        new_members.push(member);
+    }
+
+    if kept_indices.len() == original_member_count {
+        return Ok(()); // nothing pruned
+    }
+
+    let span = module.types.get_span(result_ty);
+    let pruned_ty = module.types.insert(
+        Type {
+            name: module.types[result_ty].name.clone(),
+            inner: TypeInner::Struct {
+                members: new_members,
+                span: struct_byte_span(&module.types[result_ty].inner),
+            },
+        },
+        span,
+    );
+
+    let vertex_ep = &mut module.entry_points[vertex_ep_index];
+    vertex_ep.function.result.as_mut().unwrap().ty = pruned_ty;
+    prune_return_compose(&mut vertex_ep.function, &kept_indices, result_ty, pruned_ty, original_member_count)?;
+
+    Ok(())
+}
+
+fn struct_byte_span(inner: &TypeInner) -> u32 {
+    match inner {
+        TypeInner::Struct { span, .. } => *span,
+        _ => 0,
+    }
+}
+
+/// Resolve the components of the struct value the function's final
+/// `return` produces, without mutating anything: a literal
+/// `return VOut(...)` is read directly; `var out = VOut(...); return out;`
+/// is traced through the local's single whole-value store;
+/// `var out: VOut; out.a = ...; out.b = ...; return out;` is reconstructed
+/// from the local's per-member stores. Only the function's top-level,
+/// straight-line statements are considered -- a member assigned inside an
+/// `if`/`switch`/`loop` can't be resolved statically, so that errors out
+/// rather than guessing, same as `consumed_locations`'s producer-side
+/// counterpart.
+fn resolve_return_components(
+    function: &naga::Function,
+    return_handle: Handle<Expression>,
+    original_ty: Handle<Type>,
+    original_member_count: usize,
+) -> Result<Vec<Handle<Expression>>, String> {
+    if let Expression::Compose { components, .. } = &function.expressions[return_handle] {
+        return Ok(components.clone());
+    }
+
+    let local = match function.expressions[return_handle] {
+        Expression::Load { pointer } => match function.expressions[pointer] {
+            Expression::LocalVariable(local) => local,
+            _ => {
+                return Err(
+                    "vertex entry point does not directly `return` a composed output struct, nor a plain local variable holding one"
+                        .to_string(),
+                )
+            }
+        },
+        _ => return Err("vertex entry point does not directly `return` a composed output struct".to_string()),
+    };
+
+    let mut whole_store = None;
+    let mut member_stores: HashMap<usize, Handle<Expression>> = HashMap::new();
+    for stmt in function.body.iter() {
+        if let naga::Statement::Store { pointer, value } = stmt {
+            match function.expressions[*pointer] {
+                Expression::LocalVariable(candidate) if candidate == local => {
+                    if whole_store.replace(*value).is_some() {
+                        return Err(
+                            "vertex entry point's output local is assigned more than once; can't safely trace its final struct value"
+                                .to_string(),
+                        );
+                    }
+                }
+                Expression::AccessIndex { base, index } => {
+                    if let Expression::LocalVariable(candidate) = function.expressions[base] {
+                        if candidate == local {
+                            member_stores.insert(index as usize, *value);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if whole_store.is_some() && !member_stores.is_empty() {
+        return Err(
+            "vertex entry point's output local is assigned both whole and per member; can't safely trace its final struct value"
+                .to_string(),
+        );
+    }
+
+    if let Some(value) = whole_store {
+        return match &function.expressions[value] {
+            Expression::Compose { ty, components } if *ty == original_ty => Ok(components.clone()),
+            Expression::Compose { .. } => Err(
+                "vertex entry point's output local is assigned a composed struct of a different type than its declared type".to_string(),
+            ),
+            _ => Err("vertex entry point's output local isn't assigned a literal composed struct".to_string()),
+        };
+    }
+
+    if member_stores.is_empty() {
+        return Err("vertex entry point's output local is never assigned before `return`".to_string());
+    }
+    if member_stores.len() != original_member_count {
+        return Err(format!(
+            "vertex entry point's output local assigns only {} of its {} members before `return`",
+            member_stores.len(),
+            original_member_count
+        ));
+    }
+
+    (0..original_member_count)
+        .map(|index| {
+            member_stores
+                .get(&index)
+                .copied()
+                .ok_or_else(|| format!("vertex entry point's output local never assigns member {}", index))
+        })
+        .collect()
+}
+
+/// Find the function's final `return` of the output struct, resolve its
+/// components (see [`resolve_return_components`]), and repoint the `return`
+/// at a freshly synthesized (and emitted) `Compose` holding only the
+/// components at `kept_indices`, typed as `pruned_ty`.
+fn prune_return_compose(
+    function: &mut naga::Function,
+    kept_indices: &[usize],
+    original_ty: Handle<Type>,
+    pruned_ty: Handle<Type>,
+    original_member_count: usize,
+) -> Result<(), String> {
+    let return_index = function
+        .body
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(index, stmt)| match stmt {
+            naga::Statement::Return { value: Some(_) } => Some(index),
+            _ => None,
+        })
+        .ok_or("vertex entry point body does not end in a `return` of the output struct")?;
+
+    let return_handle = match function.body.get(return_index) {
+        naga::Statement::Return { value: Some(handle) } => *handle,
+        _ => unreachable!(),
+    };
+
+    let components = resolve_return_components(function, return_handle, original_ty, original_member_count)?;
+    let pruned_components: Vec<_> = kept_indices.iter().map(|&i| components[i]).collect();
+
+    let span = function.expressions.get_span(return_handle);
+    let start_len = function.expressions.len();
+    let new_handle = function.expressions.append(
+        Expression::Compose {
+            ty: pruned_ty,
+            components: pruned_components,
+        },
+        span,
+    );
+    let emit = naga::Statement::Emit(function.expressions.range_from(start_len));
+
+    // A `Compose` is only in scope once emitted, so the `return` is preceded
+    // by an `Emit` of the new expression.
+    function.body[return_index] = naga::Statement::Block(naga::Block::from_vec(vec![
+        emit,
+        naga::Statement::Return { value: Some(new_handle) },
+    ]));
+
+    Ok(())
+}
+
+/// Parse+validate a matched vertex/fragment pair, prune the vertex stage's
+/// unconsumed outputs, and emit the (pruned) vertex stage through the
+/// normal backend dispatch.
+pub fn run(vertex_path: &Path, fragment_path: &Path, output_path: &Path, cli: &CliArgs) -> Result<(), String> {
+    let vertex_source = fs::read_to_string(vertex_path)
+        .map_err(|e| format!("Failed to read {}: {}", vertex_path.display(), e))?;
+    let fragment_source = fs::read_to_string(fragment_path)
+        .map_err(|e| format!("Failed to read {}: {}", fragment_path.display(), e))?;
+
+    let mut vertex_module =
+        naga::front::wgsl::parse_str(&vertex_source).map_err(|e| format!("WGSL parse error in {}: {}", vertex_path.display(), e))?;
+    let fragment_module = naga::front::wgsl::parse_str(&fragment_source)
+        .map_err(|e| format!("WGSL parse error in {}: {}", fragment_path.display(), e))?;
+
+    let fragment_ep = fragment_module
+        .entry_points
+        .iter()
+        .find(|ep| ep.stage == ShaderStage::Fragment)
+        .ok_or_else(|| format!("{}: no fragment entry point", fragment_path.display()))?;
+    let consumed = consumed_locations(fragment_ep, &fragment_module)?;
+
+    let vertex_ep_index = vertex_module
+        .entry_points
+        .iter()
+        .position(|ep| ep.stage == ShaderStage::Vertex)
+        .ok_or_else(|| format!("{}: no vertex entry point", vertex_path.display()))?;
+    prune_vertex_outputs(&mut vertex_module, vertex_ep_index, &consumed)?;
+
+    let file_stem = vertex_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or("Invalid filename")?;
+    crate::apply_entry_point_renames(&mut vertex_module, &cli.entry_point_renames, file_stem);
+
+    let mut validator = Validator::new(ValidationFlags::all(), Capabilities::all());
+    let module_info = validator
+        .validate(&vertex_module)
+        .map_err(|e| format!("Validation error after pruning: {}", e))?;
+
+    let ext = output_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .ok_or("Output file has no extension")?;
+    let backend = crate::backend_for_extension(ext)?;
+
+    let output = emit(
+        &vertex_module,
+        &module_info,
+        backend,
+        file_stem,
+        &cli.inline_sampler_bindings,
+        &cli.backend_options,
+    )?;
+
+    fs::write(output_path, output).map_err(|e| format!("Failed to write {}: {}", output_path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+    fn parse(source: &str) -> Module {
+        naga::front::wgsl::parse_str(source).expect("test WGSL should parse")
+    }
+
+    fn validate(module: &Module) {
+        Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(module)
+            .expect("pruned module should validate");
+    }
+
+    fn prune(vertex_source: &str, fragment_source: &str) -> Module {
+        let mut vertex_module = parse(vertex_source);
+        let fragment_module = parse(fragment_source);
+
+        let fragment_ep = fragment_module
+            .entry_points
+            .iter()
+            .find(|ep| ep.stage == ShaderStage::Fragment)
+            .expect("fragment entry point");
+        let consumed = consumed_locations(fragment_ep, &fragment_module).expect("consumed_locations should succeed");
+
+        let vertex_ep_index = vertex_module
+            .entry_points
+            .iter()
+            .position(|ep| ep.stage == ShaderStage::Vertex)
+            .expect("vertex entry point");
+        prune_vertex_outputs(&mut vertex_module, vertex_ep_index, &consumed).expect("pruning should succeed");
+
+        vertex_module
+    }
+
+    fn output_locations(module: &Module) -> Vec<u32> {
+        let ep = module.entry_points.iter().find(|ep| ep.stage == ShaderStage::Vertex).unwrap();
+        let ty = ep.function.result.as_ref().unwrap().ty;
+        match &module.types[ty].inner {
+            TypeInner::Struct { members, .. } => members
+                .iter()
+                .filter_map(|m| match m.binding {
+                    Some(Binding::Location { location, .. }) => Some(location),
+                    _ => None,
+                })
+                .collect(),
+            _ => panic!("expected struct return type"),
+        }
+    }
+
+    const VERTEX_LITERAL_COMPOSE: &str = r#"
+        struct VOut {
+            @builtin(position) position: vec4<f32>,
+            @location(0) color: vec4<f32>,
+            @location(1) uv: vec2<f32>,
+        }
+
+        @vertex
+        fn vs_main() -> VOut {
+            return VOut(vec4<f32>(0.0), vec4<f32>(1.0), vec2<f32>(0.0));
+        }
+    "#;
+
+    const VERTEX_LOCAL_WHOLE_COPY: &str = r#"
+        struct VOut {
+            @builtin(position) position: vec4<f32>,
+            @location(0) color: vec4<f32>,
+            @location(1) uv: vec2<f32>,
+        }
+
+        @vertex
+        fn vs_main() -> VOut {
+            var out = VOut(vec4<f32>(0.0), vec4<f32>(1.0), vec2<f32>(0.0));
+            return out;
+        }
+    "#;
+
+    const VERTEX_LOCAL_FIELD_BY_FIELD: &str = r#"
+        struct VOut {
+            @builtin(position) position: vec4<f32>,
+            @location(0) color: vec4<f32>,
+            @location(1) uv: vec2<f32>,
+        }
+
+        @vertex
+        fn vs_main() -> VOut {
+            var out: VOut;
+            out.position = vec4<f32>(0.0);
+            out.color = vec4<f32>(1.0);
+            out.uv = vec2<f32>(0.0);
+            return out;
+        }
+    "#;
+
+    const FRAGMENT_READS_ONLY_COLOR: &str = r#"
+        struct VOut {
+            @builtin(position) position: vec4<f32>,
+            @location(0) color: vec4<f32>,
+            @location(1) uv: vec2<f32>,
+        }
+
+        @fragment
+        fn fs_main(in: VOut) -> @location(0) vec4<f32> {
+            return in.color;
+        }
+    "#;
+
+    const FRAGMENT_FORWARDS_WHOLE_STRUCT: &str = r#"
+        struct VOut {
+            @builtin(position) position: vec4<f32>,
+            @location(0) color: vec4<f32>,
+            @location(1) uv: vec2<f32>,
+        }
+
+        fn shade(in: VOut) -> vec4<f32> {
+            return in.color * vec4<f32>(in.uv, 0.0, 1.0);
+        }
+
+        @fragment
+        fn fs_main(in: VOut) -> @location(0) vec4<f32> {
+            return shade(in);
+        }
+    "#;
+
+    #[test]
+    fn prunes_and_keeps_surviving_locations_unrenumbered() {
+        let module = prune(VERTEX_LITERAL_COMPOSE, FRAGMENT_READS_ONLY_COLOR);
+        validate(&module);
+        // `uv` (location 1) is dropped; `color` must keep its original
+        // location 0, not get renumbered.
+        assert_eq!(output_locations(&module), vec![0]);
+    }
+
+    #[test]
+    fn traces_local_whole_copy_idiom() {
+        let module = prune(VERTEX_LOCAL_WHOLE_COPY, FRAGMENT_READS_ONLY_COLOR);
+        validate(&module);
+        assert_eq!(output_locations(&module), vec![0]);
+    }
+
+    #[test]
+    fn traces_local_field_by_field_idiom() {
+        let module = prune(VERTEX_LOCAL_FIELD_BY_FIELD, FRAGMENT_READS_ONLY_COLOR);
+        validate(&module);
+        assert_eq!(output_locations(&module), vec![0]);
+    }
+
+    #[test]
+    fn whole_struct_forwarded_to_helper_is_not_pruned() {
+        let module = prune(VERTEX_LITERAL_COMPOSE, FRAGMENT_FORWARDS_WHOLE_STRUCT);
+        validate(&module);
+        // `shade` reads both `color` and `uv` through the forwarded struct,
+        // so nothing should have been pruned.
+        assert_eq!(output_locations(&module), vec![0, 1]);
+    }
+}
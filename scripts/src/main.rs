@@ -1,142 +1,645 @@
-use std::collections::BTreeMap;
+mod link;
+mod permute;
+mod reflect;
+mod sidecar;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process;
 
-use naga::back::msl;
-use naga::valid::{Capabilities, ValidationFlags, Validator};
-use naga::{AddressSpace, ResourceBinding};
+use naga::back::{glsl, hlsl, msl, spv};
+use naga::valid::{Capabilities, ModuleInfo, ValidationFlags, Validator};
+use naga::{Module, ShaderStage};
+
+use reflect::{reflect_all_bindings, reflect_bindings, BindClass};
+
+/// Output format, selected from the output file's extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    Msl,
+    Spv,
+    /// Textual SPIR-V (`.spvasm`): a word-per-line hex dump, since this tool
+    /// has no dependency on spirv-tools to produce a real disassembly.
+    SpvText,
+    Hlsl,
+    Glsl,
+}
+
+struct CliArgs {
+    positional: Vec<PathBuf>,
+    reflect_path: Option<PathBuf>,
+    inline_sampler_bindings: HashSet<naga::ResourceBinding>,
+    backend_options: BackendOptions,
+    /// Name of the permutations manifest to look for when `positional[0]`
+    /// is a directory (AOT mode). Defaults to `permutations`.
+    manifest_name: String,
+    /// Backend each permutation variant is compiled to in AOT mode.
+    target: Backend,
+    /// The fragment shader to link against, for vertex/fragment output
+    /// pruning mode (`<vertex.wgsl> <output.ext> --fragment <fragment.wgsl>`).
+    fragment_path: Option<PathBuf>,
+    /// Explicit entry-point renames (`--entry-point-rename old=new`), applied
+    /// to `Module::entry_points` before the backend ever sees the module.
+    entry_point_renames: HashMap<String, String>,
+    /// Emit one file per entry point (MSL only), named `<entry point>.msl`
+    /// under `output_path` treated as a directory.
+    split_entry_points: bool,
+}
+
+/// Knobs mirroring the ones the naga CLI exposes for its backends; today
+/// only MSL consumes `msl_version`/`zero_initialize_workgroup_memory`/
+/// `force_loop_bounding`, but `bounds_check_policies` applies to every
+/// backend that takes one.
+struct BackendOptions {
+    bounds_check_policies: naga::proc::BoundsCheckPolicies,
+    msl_version: (u8, u8),
+    zero_initialize_workgroup_memory: bool,
+    force_loop_bounding: bool,
+}
+
+impl Default for BackendOptions {
+    fn default() -> Self {
+        BackendOptions {
+            bounds_check_policies: naga::proc::BoundsCheckPolicies::default(),
+            msl_version: (1, 0),
+            zero_initialize_workgroup_memory: true,
+            force_loop_bounding: false,
+        }
+    }
+}
+
+/// Parse a `group:binding` pair, as used by `--inline-sampler`.
+fn parse_resource_binding(s: &str) -> Result<naga::ResourceBinding, String> {
+    let (group, binding) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected group:binding, got `{}`", s))?;
+    Ok(naga::ResourceBinding {
+        group: group.parse().map_err(|_| format!("invalid group in `{}`", s))?,
+        binding: binding.parse().map_err(|_| format!("invalid binding in `{}`", s))?,
+    })
+}
+
+fn parse_bounds_check_policy(s: &str) -> Result<naga::proc::BoundsCheckPolicy, String> {
+    match s {
+        "Restrict" => Ok(naga::proc::BoundsCheckPolicy::Restrict),
+        "ReadZeroSkipWrite" => Ok(naga::proc::BoundsCheckPolicy::ReadZeroSkipWrite),
+        "Unchecked" => Ok(naga::proc::BoundsCheckPolicy::Unchecked),
+        other => Err(format!(
+            "invalid bounds-check policy `{}` (expected Restrict, ReadZeroSkipWrite, or Unchecked)",
+            other
+        )),
+    }
+}
+
+fn parse_msl_version(s: &str) -> Result<(u8, u8), String> {
+    let (major, minor) = s
+        .split_once('.')
+        .ok_or_else(|| format!("expected MAJOR.MINOR, got `{}`", s))?;
+    Ok((
+        major.parse().map_err(|_| format!("invalid MSL major version in `{}`", s))?,
+        minor.parse().map_err(|_| format!("invalid MSL minor version in `{}`", s))?,
+    ))
+}
+
+fn parse_bool_flag(s: &str) -> Result<bool, String> {
+    match s {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(format!("expected true or false, got `{}`", other)),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<CliArgs, String> {
+    let mut positional = Vec::new();
+    let mut reflect_path = None;
+    let mut inline_sampler_bindings = HashSet::new();
+    let mut index_policy = None;
+    let mut buffer_policy = None;
+    let mut image_policy = None;
+    let mut backend_options = BackendOptions::default();
+    let mut manifest_name = "permutations".to_string();
+    let mut target = Backend::Msl;
+    let mut fragment_path = None;
+    let mut entry_point_renames = HashMap::new();
+    let mut split_entry_points = false;
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--reflect" => {
+                let path = iter.next().ok_or("--reflect requires a path argument")?;
+                reflect_path = Some(PathBuf::from(path));
+            }
+            "--inline-sampler" => {
+                let spec = iter.next().ok_or("--inline-sampler requires a group:binding argument")?;
+                inline_sampler_bindings.insert(parse_resource_binding(spec)?);
+            }
+            "--index-bounds-check-policy" => {
+                let spec = iter.next().ok_or("--index-bounds-check-policy requires a value")?;
+                index_policy = Some(parse_bounds_check_policy(spec)?);
+            }
+            "--buffer-bounds-check-policy" => {
+                let spec = iter.next().ok_or("--buffer-bounds-check-policy requires a value")?;
+                buffer_policy = Some(parse_bounds_check_policy(spec)?);
+            }
+            "--image-bounds-check-policy" => {
+                let spec = iter.next().ok_or("--image-bounds-check-policy requires a value")?;
+                image_policy = Some(parse_bounds_check_policy(spec)?);
+            }
+            "--msl-version" => {
+                let spec = iter.next().ok_or("--msl-version requires a MAJOR.MINOR value")?;
+                backend_options.msl_version = parse_msl_version(spec)?;
+            }
+            "--msl-zero-initialize-workgroup-memory" => {
+                let spec = iter
+                    .next()
+                    .ok_or("--msl-zero-initialize-workgroup-memory requires true or false")?;
+                backend_options.zero_initialize_workgroup_memory = parse_bool_flag(spec)?;
+            }
+            "--msl-force-loop-bounding" => {
+                let spec = iter.next().ok_or("--msl-force-loop-bounding requires true or false")?;
+                backend_options.force_loop_bounding = parse_bool_flag(spec)?;
+            }
+            "--manifest" => {
+                let spec = iter.next().ok_or("--manifest requires a file name")?;
+                manifest_name = spec.clone();
+            }
+            "--target" => {
+                let spec = iter.next().ok_or("--target requires a backend name")?;
+                target = backend_for_extension(spec)?;
+            }
+            "--fragment" => {
+                let spec = iter.next().ok_or("--fragment requires a path argument")?;
+                fragment_path = Some(PathBuf::from(spec));
+            }
+            "--entry-point-rename" => {
+                let spec = iter.next().ok_or("--entry-point-rename requires an old=new argument")?;
+                let (old, new) = spec
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected old=new, got `{}`", spec))?;
+                entry_point_renames.insert(old.to_string(), new.to_string());
+            }
+            "--split-entry-points" => split_entry_points = true,
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if positional.is_empty() || positional.len() > 2 {
+        return Err("expected <input.wgsl> <output.ext>, or <shader-dir> [out.rs] for permutation mode".to_string());
+    }
+
+    // Buffer/image policies default to the index policy when omitted, matching the naga CLI.
+    let index = index_policy.unwrap_or_default();
+    backend_options.bounds_check_policies = naga::proc::BoundsCheckPolicies {
+        index,
+        buffer: buffer_policy.unwrap_or(index),
+        image: image_policy.unwrap_or(index),
+        ..Default::default()
+    };
+
+    Ok(CliArgs {
+        positional: positional.into_iter().map(PathBuf::from).collect(),
+        reflect_path,
+        inline_sampler_bindings,
+        backend_options,
+        manifest_name,
+        target,
+        fragment_path,
+        entry_point_renames,
+        split_entry_points,
+    })
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 3 {
-        eprintln!("Usage: {} <input.wgsl> <output.msl>", args[0]);
-        process::exit(1);
-    }
+    let cli = match parse_args(&args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: {} <input.wgsl> <output.{{msl,spv,spvasm,hlsl,glsl,vert,frag}}> \
+                 [--reflect out.json] [--inline-sampler group:binding]... \
+                 [--index-bounds-check-policy Restrict|ReadZeroSkipWrite|Unchecked] \
+                 [--buffer-bounds-check-policy ...] [--image-bounds-check-policy ...] \
+                 [--msl-version MAJOR.MINOR] [--msl-zero-initialize-workgroup-memory true|false] \
+                 [--msl-force-loop-bounding true|false] [--entry-point-rename old=new]... \
+                 [--split-entry-points]\n\
+                 \n\
+                 AOT permutation mode: {} <shader-dir> [out.rs] [--manifest permutations]\n\
+                 Vertex/fragment linking: {} <vertex.wgsl> <output.ext> --fragment <fragment.wgsl>",
+                args[0], args[0], args[0]
+            );
+            process::exit(1);
+        }
+    };
 
-    let input_path = PathBuf::from(&args[1]);
-    let output_path = PathBuf::from(&args[2]);
+    let result = match cli.positional.as_slice() {
+        [dir] if dir.is_dir() => permute::run(dir, None, &cli),
+        [dir, out] if dir.is_dir() => permute::run(dir, Some(out), &cli),
+        [vertex_path, output_path] if cli.fragment_path.is_some() => {
+            link::run(vertex_path, cli.fragment_path.as_deref().unwrap(), output_path, &cli)
+        }
+        [input_path, output_path] => convert_wgsl(input_path, output_path, &cli),
+        _ => Err("expected <input.wgsl> <output.ext>, or an existing <shader-dir>".to_string()),
+    };
 
-    if let Err(e) = convert_wgsl_to_msl(&input_path, &output_path) {
+    if let Err(e) = result {
         eprintln!("Error: {}", e);
         process::exit(1);
     }
 }
 
-fn convert_wgsl_to_msl(input_path: &Path, output_path: &Path) -> Result<(), String> {
+fn backend_for_extension(ext: &str) -> Result<Backend, String> {
+    match ext {
+        "msl" => Ok(Backend::Msl),
+        "spv" => Ok(Backend::Spv),
+        "spvasm" => Ok(Backend::SpvText),
+        "hlsl" => Ok(Backend::Hlsl),
+        "glsl" | "vert" | "frag" => Ok(Backend::Glsl),
+        other => Err(format!("Unrecognized output extension: .{}", other)),
+    }
+}
+
+fn convert_wgsl(input_path: &Path, output_path: &Path, cli: &CliArgs) -> Result<(), String> {
     // Read WGSL source
     let wgsl_source = fs::read_to_string(input_path)
         .map_err(|e| format!("Failed to read {}: {}", input_path.display(), e))?;
 
     // Parse WGSL
-    let module = naga::front::wgsl::parse_str(&wgsl_source)
+    let mut module = naga::front::wgsl::parse_str(&wgsl_source)
         .map_err(|e| format!("WGSL parse error: {}", e))?;
 
+    let file_stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or("Invalid filename")?;
+    apply_entry_point_renames(&mut module, &cli.entry_point_renames, file_stem);
+
     // Validate module
     let mut validator = Validator::new(ValidationFlags::all(), Capabilities::all());
     let module_info = validator
         .validate(&module)
         .map_err(|e| format!("Validation error: {}", e))?;
 
-    // Determine entrypoint name from filename
-    let file_stem = input_path
-        .file_stem()
+    let ext = output_path
+        .extension()
         .and_then(|s| s.to_str())
-        .ok_or("Invalid filename")?;
+        .ok_or("Output file has no extension")?;
+    let backend = backend_for_extension(ext)?;
 
-    let entrypoint_name = determine_entrypoint_name(file_stem);
+    if cli.split_entry_points {
+        if backend != Backend::Msl {
+            return Err(format!(
+                "--split-entry-points is only supported for the msl backend, got .{}",
+                ext
+            ));
+        }
+        write_msl_split(
+            &module,
+            &module_info,
+            output_path,
+            &cli.inline_sampler_bindings,
+            &cli.backend_options,
+        )?;
+    } else {
+        let output = emit(
+            &module,
+            &module_info,
+            backend,
+            file_stem,
+            &cli.inline_sampler_bindings,
+            &cli.backend_options,
+        )?;
 
-    // Build binding map for all entry points
-    // Map group(0) binding(0) to buffer(0)
-    let mut per_entry_point_map = BTreeMap::new();
+        fs::write(output_path, output)
+            .map_err(|e| format!("Failed to write {}: {}", output_path.display(), e))?;
+    }
 
-    for (ep_index, entry_point) in module.entry_points.iter().enumerate() {
-        let mut resources = BTreeMap::new();
-        let ep_info = module_info.get_entry_point(ep_index);
+    if let Some(reflect_path) = &cli.reflect_path {
+        let shader_info = sidecar::collect(&module, &module_info)?;
+        fs::write(reflect_path, shader_info.to_json())
+            .map_err(|e| format!("Failed to write {}: {}", reflect_path.display(), e))?;
+    }
 
-        // For each global variable in the module
-        for (handle, global_var) in module.global_variables.iter() {
-            // Check if this variable is used by this entry point
-            if !ep_info[handle].is_empty() {
-                // If the variable has a binding
-                if let Some(ref binding) = global_var.binding {
-                    let resource_binding = ResourceBinding {
-                        group: binding.group,
-                        binding: binding.binding,
-                    };
-
-                    // Map to Metal buffer slot
-                    // We use buffer slots for uniform and storage buffers
-                    let bind_target = match global_var.space {
-                        AddressSpace::Uniform | AddressSpace::Storage { .. } => {
-                            msl::BindTarget {
-                                buffer: Some(binding.binding as u8),
-                                texture: None,
-                                sampler: None,
-                                mutable: matches!(
-                                    global_var.space,
-                                    AddressSpace::Storage { access } if access.contains(naga::StorageAccess::STORE)
-                                ),
-                            }
-                        }
-                        _ => continue,
-                    };
-
-                    resources.insert(resource_binding, bind_target);
+    Ok(())
+}
+
+/// Apply explicit `--entry-point-rename old=new` mappings to `module`'s entry
+/// points; any entry point left unmapped falls back to the filename-convention
+/// default from [`determine_entrypoint_name`], if there is one. Runs before
+/// validation and codegen so every backend sees the final name directly,
+/// instead of patching generated source text afterwards.
+///
+/// The filename-convention fallback only applies when the module has a
+/// single entry point -- with more than one, every unmapped entry point
+/// would collapse onto the same default name (e.g. two vertex variants in
+/// one file both becoming `main_vertex`), silently clobbering one another's
+/// output file and resource map entry. Modules with multiple entry points
+/// must rename each one explicitly.
+pub(crate) fn apply_entry_point_renames(module: &mut Module, renames: &HashMap<String, String>, file_stem: &str) {
+    let default_name = match module.entry_points.as_slice() {
+        [_] => determine_entrypoint_name(file_stem),
+        _ => None,
+    };
+    for entry_point in &mut module.entry_points {
+        if let Some(new_name) = renames.get(&entry_point.name) {
+            entry_point.name = new_name.clone();
+        } else if let Some(default_name) = default_name {
+            entry_point.name = default_name.to_string();
+        }
+    }
+}
+
+/// Dispatch to the chosen backend writer and return the bytes to write out.
+/// Shared between single-file mode, AOT permutation mode, and vertex/
+/// fragment linking, all of which part ways only after parse+validate.
+pub(crate) fn emit(
+    module: &Module,
+    module_info: &ModuleInfo,
+    backend: Backend,
+    file_stem: &str,
+    inline_sampler_bindings: &HashSet<naga::ResourceBinding>,
+    backend_options: &BackendOptions,
+) -> Result<Vec<u8>, String> {
+    Ok(match backend {
+        Backend::Msl => write_msl(module, module_info, inline_sampler_bindings, backend_options)?.into_bytes(),
+        Backend::Spv => spv_to_bytes(&write_spv(module, module_info, backend_options)?),
+        Backend::SpvText => write_spv_text(&write_spv(module, module_info, backend_options)?).into_bytes(),
+        Backend::Hlsl => write_hlsl(module, module_info)?.into_bytes(),
+        Backend::Glsl => {
+            let stage = stage_from_file_stem(file_stem);
+            write_glsl(module, module_info, stage, backend_options)?.into_bytes()
+        }
+    })
+}
+
+/// Turn a generic [`reflect::BindingInfo`] into the MSL backend's own
+/// `BindTarget`, assigning a separate slot range per resource class (Metal
+/// keeps buffers, textures and samplers in distinct argument tables).
+/// Samplers in `inline_sampler_bindings` are instead baked into the shader
+/// as `BindTarget::sampler = Some(BindSamplerTarget::Inline(..))`, appending
+/// the Metal-default sampler state to `inline_samplers`.
+fn msl_resources(
+    module: &Module,
+    ep_info: &naga::valid::FunctionInfo,
+    inline_sampler_bindings: &HashSet<naga::ResourceBinding>,
+    inline_samplers: &mut Vec<msl::sampler::InlineSampler>,
+) -> BTreeMap<naga::ResourceBinding, msl::BindTarget> {
+    let mut resources = BTreeMap::new();
+    let mut next_buffer_slot = 0u8;
+    let mut next_texture_slot = 0u8;
+    let mut next_sampler_slot = 0u8;
+
+    for binding in reflect_bindings(module, ep_info) {
+        let bind_target = match binding.class {
+            BindClass::UniformBuffer | BindClass::StorageBuffer { .. } => {
+                let slot = next_buffer_slot;
+                next_buffer_slot += 1;
+                msl::BindTarget {
+                    buffer: Some(slot),
+                    texture: None,
+                    sampler: None,
+                    mutable: matches!(binding.class, BindClass::StorageBuffer { mutable: true }),
                 }
             }
-        }
+            BindClass::Texture => {
+                let slot = next_texture_slot;
+                next_texture_slot += 1;
+                msl::BindTarget {
+                    buffer: None,
+                    texture: Some(slot),
+                    sampler: None,
+                    mutable: false,
+                }
+            }
+            BindClass::Sampler { .. } => {
+                let sampler = if inline_sampler_bindings.contains(&binding.resource_binding) {
+                    let index = inline_samplers.len();
+                    inline_samplers.push(msl::sampler::InlineSampler::default());
+                    msl::BindSamplerTarget::Inline(index)
+                } else {
+                    let slot = next_sampler_slot;
+                    next_sampler_slot += 1;
+                    msl::BindSamplerTarget::Resource(slot)
+                };
+                msl::BindTarget {
+                    buffer: None,
+                    texture: None,
+                    sampler: Some(sampler),
+                    mutable: false,
+                }
+            }
+        };
+
+        resources.insert(binding.resource_binding, bind_target);
+    }
 
+    resources
+}
+
+/// Build the `msl::Options` shared by whole-module and per-entry-point MSL
+/// generation: the resource map and inline-sampler table depend only on the
+/// module and the chosen bindings, not on which entry point(s) end up in the
+/// output.
+fn msl_options(
+    module: &Module,
+    module_info: &ModuleInfo,
+    inline_sampler_bindings: &HashSet<naga::ResourceBinding>,
+    backend_options: &BackendOptions,
+) -> msl::Options {
+    let mut per_entry_point_map = BTreeMap::new();
+    let mut inline_samplers = Vec::new();
+
+    for (ep_index, entry_point) in module.entry_points.iter().enumerate() {
+        let ep_info = module_info.get_entry_point(ep_index);
         let entry_point_resources = msl::EntryPointResources {
-            resources,
+            resources: msl_resources(module, ep_info, inline_sampler_bindings, &mut inline_samplers),
             push_constant_buffer: None,
             sizes_buffer: None,
         };
-
         per_entry_point_map.insert(entry_point.name.clone(), entry_point_resources);
     }
 
-    // Configure MSL options
-    let options = msl::Options {
-        lang_version: (1, 0),
+    msl::Options {
+        lang_version: backend_options.msl_version,
         per_entry_point_map,
-        inline_samplers: Vec::new(),
+        inline_samplers,
         spirv_cross_compatibility: false,
-        fake_missing_bindings: false,  // This is the key - we provide real bindings!
-        bounds_check_policies: Default::default(),
-        zero_initialize_workgroup_memory: true,
-        force_loop_bounding: false,  // Don't inject loop bounding code
-    };
+        fake_missing_bindings: false, // This is the key - we provide real bindings!
+        bounds_check_policies: backend_options.bounds_check_policies,
+        zero_initialize_workgroup_memory: backend_options.zero_initialize_workgroup_memory,
+        force_loop_bounding: backend_options.force_loop_bounding,
+    }
+}
 
-    // Generate MSL
+fn write_msl(
+    module: &Module,
+    module_info: &ModuleInfo,
+    inline_sampler_bindings: &HashSet<naga::ResourceBinding>,
+    backend_options: &BackendOptions,
+) -> Result<String, String> {
+    let options = msl_options(module, module_info, inline_sampler_bindings, backend_options);
     let pipeline_options = msl::PipelineOptions {
-        entry_point: None,  // Write all entry points
+        entry_point: None, // Write all entry points
         allow_and_force_point_size: false,
         vertex_pulling_transform: false,
         vertex_buffer_mappings: Default::default(),
     };
 
-    let (msl_source, _) = msl::write_string(&module, &module_info, &options, &pipeline_options)
+    let (msl_source, _) = msl::write_string(module, module_info, &options, &pipeline_options)
         .map_err(|e| format!("MSL generation error: {:?}", e))?;
 
-    // Rename entrypoint if needed
-    let msl_final = if let Some(new_name) = entrypoint_name {
-        rename_entrypoint(&msl_source, "main_", new_name)
-    } else {
-        msl_source
-    };
+    Ok(msl_source)
+}
+
+/// Write one `<entry point name>.msl` file per entry point into `output_dir`,
+/// rather than naga's usual single file holding every entry point -- lets a
+/// downstream Metal build step compile each function as its own library.
+fn write_msl_split(
+    module: &Module,
+    module_info: &ModuleInfo,
+    output_dir: &Path,
+    inline_sampler_bindings: &HashSet<naga::ResourceBinding>,
+    backend_options: &BackendOptions,
+) -> Result<(), String> {
+    let options = msl_options(module, module_info, inline_sampler_bindings, backend_options);
 
-    // Write output
-    fs::write(output_path, msl_final)
-        .map_err(|e| format!("Failed to write {}: {}", output_path.display(), e))?;
+    fs::create_dir_all(output_dir).map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
+
+    for entry_point in &module.entry_points {
+        let pipeline_options = msl::PipelineOptions {
+            entry_point: Some(entry_point.name.clone()),
+            allow_and_force_point_size: false,
+            vertex_pulling_transform: false,
+            vertex_buffer_mappings: Default::default(),
+        };
+
+        let (msl_source, _) = msl::write_string(module, module_info, &options, &pipeline_options)
+            .map_err(|e| format!("MSL generation error: {:?}", e))?;
+
+        let file_path = output_dir.join(format!("{}.msl", entry_point.name));
+        fs::write(&file_path, msl_source).map_err(|e| format!("Failed to write {}: {}", file_path.display(), e))?;
+    }
 
     Ok(())
 }
 
+/// Assign each resource binding a flat slot number, in the order the shared
+/// reflection pass returns them (entry point by entry point, globals in
+/// declaration order, first use wins) -- used by the backends (SPIR-V,
+/// GLSL, HLSL) whose `binding_map` covers the whole module rather than one
+/// entry point's resources at a time like MSL's `BindTarget`.
+fn flat_binding_map(module: &Module, module_info: &ModuleInfo) -> BTreeMap<naga::ResourceBinding, u8> {
+    reflect_all_bindings(module, module_info)
+        .into_iter()
+        .enumerate()
+        .map(|(slot, binding)| (binding.resource_binding, slot as u8))
+        .collect()
+}
+
+fn write_spv(module: &Module, module_info: &ModuleInfo, backend_options: &BackendOptions) -> Result<Vec<u32>, String> {
+    let options = spv::Options {
+        lang_version: (1, 0),
+        flags: spv::WriterFlags::empty(),
+        binding_map: flat_binding_map(module, module_info),
+        capabilities: None,
+        bounds_check_policies: backend_options.bounds_check_policies,
+    };
+
+    spv::write_vec(module, module_info, &options, None)
+        .map_err(|e| format!("SPIR-V generation error: {:?}", e))
+}
+
+fn spv_to_bytes(words: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(words.len() * 4);
+    for word in words {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+/// Textual SPIR-V output (`.spvasm`): a word-per-line hex dump. Not a real
+/// disassembly -- opcodes aren't decoded -- since that needs spirv-tools,
+/// which this tool doesn't depend on; it's enough to diff or skim a module
+/// without a binary viewer.
+fn write_spv_text(words: &[u32]) -> String {
+    let mut out = String::new();
+    out.push_str("; SPIR-V module as a flat word dump (not a full disassembly)\n");
+    for (index, word) in words.iter().enumerate() {
+        out.push_str(&format!("{:>6}: 0x{:08x}\n", index, word));
+    }
+    out
+}
+
+fn write_hlsl(module: &Module, module_info: &ModuleInfo) -> Result<String, String> {
+    let options = hlsl::Options {
+        binding_map: flat_binding_map(module, module_info),
+        ..Default::default()
+    };
+
+    let mut buffer = String::new();
+    let mut writer = hlsl::Writer::new(&mut buffer, &options);
+    writer
+        .write(module, module_info)
+        .map_err(|e| format!("HLSL generation error: {:?}", e))?;
+    Ok(buffer)
+}
+
+fn write_glsl(
+    module: &Module,
+    module_info: &ModuleInfo,
+    stage: ShaderStage,
+    backend_options: &BackendOptions,
+) -> Result<String, String> {
+    let entry_point = module
+        .entry_points
+        .iter()
+        .find(|ep| ep.stage == stage)
+        .ok_or_else(|| format!("No {:?} entry point in module", stage))?;
+
+    let options = glsl::Options {
+        version: glsl::Version::Desktop(330),
+        writer_flags: glsl::WriterFlags::empty(),
+        binding_map: flat_binding_map(module, module_info),
+    };
+    let pipeline_options = glsl::PipelineOptions {
+        shader_stage: stage,
+        entry_point: entry_point.name.clone(),
+        multiview: None,
+    };
+
+    let mut buffer = String::new();
+    let mut writer = glsl::Writer::new(
+        &mut buffer,
+        module,
+        module_info,
+        &options,
+        &pipeline_options,
+        backend_options.bounds_check_policies,
+    )
+    .map_err(|e| format!("GLSL writer setup error: {:?}", e))?;
+    writer
+        .write()
+        .map_err(|e| format!("GLSL generation error: {:?}", e))?;
+    Ok(buffer)
+}
+
+/// A vertex/fragment pair sharing a filename stem is distinguished by suffix;
+/// anything that isn't explicitly a fragment shader is treated as a vertex one.
+fn stage_from_file_stem(file_stem: &str) -> ShaderStage {
+    if file_stem.ends_with("_fragment") {
+        ShaderStage::Fragment
+    } else {
+        ShaderStage::Vertex
+    }
+}
+
+/// Filename-convention default for an entry point's final name, used by
+/// [`apply_entry_point_renames`] when `--entry-point-rename` doesn't cover it.
 fn determine_entrypoint_name(file_stem: &str) -> Option<&'static str> {
-    // Determine target entrypoint name based on filename convention
     if file_stem.ends_with("_vertex") {
         if file_stem.contains("_overlay_") {
             Some("overlay_vertex")
@@ -153,15 +656,3 @@ fn determine_entrypoint_name(file_stem: &str) -> Option<&'static str> {
         None
     }
 }
-
-fn rename_entrypoint(msl_source: &str, old_name: &str, new_name: &str) -> String {
-    // Replace function definition
-    // Look for patterns like "vertex main_Output main_(" or "fragment main_Output main_("
-    let vertex_pattern = format!("vertex main_Output {}(", old_name);
-    let fragment_pattern = format!("fragment main_Output {}(", old_name);
-
-    let result = msl_source.replace(&vertex_pattern, &format!("vertex main_Output {}(", new_name));
-    let result = result.replace(&fragment_pattern, &format!("fragment main_Output {}(", new_name));
-
-    result
-}
@@ -0,0 +1,233 @@
+//! Directory-wide shader permutation / AOT compilation, modeled on Vello's
+//! shader-permutation build step: a `permutations` manifest lists a base
+//! WGSL file and the `#define`-driven variants to compile it into. Each
+//! variant is preprocessed, then runs through the same parse+validate+write
+//! pipeline as single-file mode, and the whole set is baked into a
+//! generated Rust module so downstream crates get statically-available,
+//! specialized shader binaries.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+use crate::{sidecar, Backend, CliArgs};
+
+struct Variant {
+    name: String,
+    defines: HashSet<String>,
+}
+
+struct ManifestEntry {
+    base: String,
+    variants: Vec<Variant>,
+}
+
+struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+/// Parse a `permutations` manifest: an unindented base shader name line
+/// followed by indented `variant_name: DEFINE_A, DEFINE_B` lines.
+fn parse_manifest(text: &str) -> Result<Manifest, String> {
+    let mut entries = Vec::new();
+    let mut current: Option<ManifestEntry> = None;
+
+    for (lineno, raw_line) in text.lines().enumerate() {
+        if raw_line.trim().is_empty() {
+            continue;
+        }
+        let indented = raw_line.starts_with(' ') || raw_line.starts_with('\t');
+        let line = raw_line.trim();
+
+        if indented {
+            let entry = current
+                .as_mut()
+                .ok_or_else(|| format!("line {}: variant line with no preceding base shader", lineno + 1))?;
+            let (name, defines) = line
+                .split_once(':')
+                .ok_or_else(|| format!("line {}: expected `variant_name: DEFINE_A, DEFINE_B`", lineno + 1))?;
+            let defines = defines
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+            entry.variants.push(Variant {
+                name: name.trim().to_string(),
+                defines,
+            });
+        } else {
+            if let Some(entry) = current.take() {
+                entries.push(entry);
+            }
+            current = Some(ManifestEntry {
+                base: line.to_string(),
+                variants: Vec::new(),
+            });
+        }
+    }
+    if let Some(entry) = current.take() {
+        entries.push(entry);
+    }
+
+    Ok(Manifest { entries })
+}
+
+/// One level of `#ifdef`/`#else` nesting; `parent_active` is frozen at
+/// `#ifdef` time so a sibling `#else` only flips this level, not outer ones.
+struct CondFrame {
+    parent_active: bool,
+    condition: bool,
+}
+
+fn stack_active(stack: &[CondFrame]) -> bool {
+    stack.last().map_or(true, |f| f.parent_active && f.condition)
+}
+
+/// Resolve `#ifdef`/`#else`/`#endif`/`#import` against `defines`, splicing in
+/// `#import other_file` from the same directory. `visited` dedupes imports
+/// (by canonical path) so a diamond or cycle doesn't recurse forever.
+fn preprocess(
+    dir: &Path,
+    file_stem: &str,
+    defines: &HashSet<String>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<String, String> {
+    let path = dir.join(format!("{}.wgsl", file_stem));
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+    if !visited.insert(canonical) {
+        return Ok(String::new());
+    }
+
+    let source = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let mut out = String::new();
+    let mut stack: Vec<CondFrame> = Vec::new();
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+
+        if let Some(name) = trimmed.strip_prefix("#ifdef ") {
+            let parent_active = stack_active(&stack);
+            stack.push(CondFrame {
+                parent_active,
+                condition: defines.contains(name.trim()),
+            });
+            continue;
+        }
+        if trimmed == "#else" {
+            let top = stack.last_mut().ok_or_else(|| format!("{}: #else with no matching #ifdef", path.display()))?;
+            top.condition = !top.condition;
+            continue;
+        }
+        if trimmed == "#endif" {
+            stack.pop().ok_or_else(|| format!("{}: #endif with no matching #ifdef", path.display()))?;
+            continue;
+        }
+
+        if !stack_active(&stack) {
+            continue;
+        }
+
+        if let Some(import) = trimmed.strip_prefix("#import ") {
+            out.push_str(&preprocess(dir, import.trim(), defines, visited)?);
+            out.push('\n');
+            continue;
+        }
+
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    if !stack.is_empty() {
+        return Err(format!("{}: unterminated #ifdef", path.display()));
+    }
+
+    Ok(out)
+}
+
+struct CompiledVariant {
+    name: String,
+    source: Vec<u8>,
+    reflection_json: String,
+}
+
+fn compile_variant(
+    dir: &Path,
+    entry: &ManifestEntry,
+    variant: &Variant,
+    cli: &CliArgs,
+) -> Result<CompiledVariant, String> {
+    let mut visited = HashSet::new();
+    let wgsl_source = preprocess(dir, &entry.base, &variant.defines, &mut visited)?;
+
+    let module = naga::front::wgsl::parse_str(&wgsl_source)
+        .map_err(|e| format!("{}/{}: WGSL parse error: {}", entry.base, variant.name, e))?;
+    let mut validator = Validator::new(ValidationFlags::all(), Capabilities::all());
+    let module_info = validator
+        .validate(&module)
+        .map_err(|e| format!("{}/{}: validation error: {}", entry.base, variant.name, e))?;
+
+    let source = crate::emit(
+        &module,
+        &module_info,
+        cli.target,
+        &entry.base,
+        &cli.inline_sampler_bindings,
+        &cli.backend_options,
+    )?;
+    let reflection_json = sidecar::collect(&module, &module_info)?.to_json();
+
+    Ok(CompiledVariant {
+        name: variant.name.clone(),
+        source,
+        reflection_json,
+    })
+}
+
+/// Compile every variant in `dir`'s manifest and write the generated Rust
+/// module to `out_path`, or `$OUT_DIR/shader_variants.rs` when run from a
+/// build script and `out_path` is omitted.
+pub fn run(dir: &Path, out_path: Option<&Path>, cli: &CliArgs) -> Result<(), String> {
+    let manifest_path = dir.join(&cli.manifest_name);
+    let manifest_text = fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("Failed to read manifest {}: {}", manifest_path.display(), e))?;
+    let manifest = parse_manifest(&manifest_text)?;
+
+    let mut compiled = Vec::new();
+    for entry in &manifest.entries {
+        for variant in &entry.variants {
+            compiled.push(compile_variant(dir, entry, variant, cli)?);
+        }
+    }
+
+    let out_path = match out_path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let out_dir = env::var("OUT_DIR")
+                .map_err(|_| "no output path given and OUT_DIR is not set".to_string())?;
+            PathBuf::from(out_dir).join("shader_variants.rs")
+        }
+    };
+
+    fs::write(&out_path, render_module(&compiled))
+        .map_err(|e| format!("Failed to write {}: {}", out_path.display(), e))
+}
+
+fn render_module(variants: &[CompiledVariant]) -> String {
+    let mut out = String::new();
+    out.push_str("// @generated by the shader permutation compiler. Do not edit by hand.\n\n");
+    out.push_str("pub struct CompiledVariant {\n    pub source: &'static [u8],\n    pub reflection_json: &'static str,\n}\n\n");
+    out.push_str("pub static VARIANTS: &[(&str, CompiledVariant)] = &[\n");
+    for variant in variants {
+        out.push_str(&format!(
+            "    ({:?}, CompiledVariant {{ source: &{:?}, reflection_json: {:?} }}),\n",
+            variant.name, variant.source, variant.reflection_json
+        ));
+    }
+    out.push_str("];\n");
+    out
+}
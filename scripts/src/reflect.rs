@@ -0,0 +1,137 @@
+//! Backend-agnostic reflection over a module's global variables.
+//!
+//! Each backend (MSL, HLSL, GLSL, ...) needs to know which resource bindings
+//! a given entry point actually touches and how they should be classified;
+//! this walk is identical regardless of which backend consumes the result,
+//! so it lives here instead of being duplicated per writer.
+
+use naga::valid::{FunctionInfo, ModuleInfo};
+use naga::{AddressSpace, Module, ResourceBinding, StorageAccess, TypeInner};
+
+/// How a binding should be treated by a backend, independent of the target
+/// language's own option types (e.g. `msl::BindTarget`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindClass {
+    UniformBuffer,
+    StorageBuffer { mutable: bool },
+    Texture,
+    Sampler { comparison: bool },
+}
+
+/// A single resource binding used by one entry point.
+#[derive(Clone, Copy, Debug)]
+pub struct BindingInfo {
+    pub resource_binding: ResourceBinding,
+    pub class: BindClass,
+    /// Element stride, in bytes, if the binding's type is a runtime-sized
+    /// array (so the host can size its allocation for it).
+    pub runtime_array_stride: Option<u32>,
+}
+
+/// Walk `module.global_variables`, keeping only the ones `ep_info` marks as
+/// used by the entry point, and classify each by address space.
+pub fn reflect_bindings(module: &Module, ep_info: &FunctionInfo) -> Vec<BindingInfo> {
+    let mut bindings = Vec::new();
+
+    for (handle, global_var) in module.global_variables.iter() {
+        if ep_info[handle].is_empty() {
+            continue;
+        }
+
+        let binding = match global_var.binding {
+            Some(ref binding) => binding,
+            None => continue,
+        };
+
+        let class = match global_var.space {
+            AddressSpace::Uniform => BindClass::UniformBuffer,
+            AddressSpace::Storage { access } => BindClass::StorageBuffer {
+                mutable: access.contains(StorageAccess::STORE),
+            },
+            AddressSpace::Handle => match module.types[global_var.ty].inner {
+                TypeInner::Image { .. } => BindClass::Texture,
+                TypeInner::Sampler { comparison } => BindClass::Sampler { comparison },
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        bindings.push(BindingInfo {
+            resource_binding: ResourceBinding {
+                group: binding.group,
+                binding: binding.binding,
+            },
+            class,
+            runtime_array_stride: runtime_array_stride(module, global_var.ty),
+        });
+    }
+
+    bindings
+}
+
+/// Like [`reflect_bindings`], but unioned over every entry point in the
+/// module instead of one. Backends that take a single module-wide binding
+/// map (SPIR-V, GLSL, HLSL) need one covering every entry point, unlike
+/// MSL's `per_entry_point_map`.
+pub fn reflect_all_bindings(module: &Module, module_info: &ModuleInfo) -> Vec<BindingInfo> {
+    let mut bindings = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+
+    for (ep_index, _) in module.entry_points.iter().enumerate() {
+        let ep_info = module_info.get_entry_point(ep_index);
+        for binding in reflect_bindings(module, ep_info) {
+            if seen.insert(binding.resource_binding) {
+                bindings.push(binding);
+            }
+        }
+    }
+
+    bindings
+}
+
+/// If `ty` is a runtime-sized array (`[T]` with no fixed size), return the
+/// byte stride of its element type; otherwise `None`.
+fn runtime_array_stride(module: &Module, ty: naga::Handle<naga::Type>) -> Option<u32> {
+    match module.types[ty].inner {
+        TypeInner::Array {
+            size: naga::ArraySize::Dynamic,
+            stride,
+            ..
+        } => Some(stride),
+        _ => None,
+    }
+}
+
+/// A `workgroup`-space global used as compute scratch space, with its byte
+/// size so the host can allocate it.
+#[derive(Clone, Debug)]
+pub struct WorkgroupBufferInfo {
+    pub name: Option<String>,
+    pub size: u32,
+}
+
+/// Collect the `workgroup`-address-space globals an entry point touches,
+/// sized via the module's type layout.
+pub fn reflect_workgroup_buffers(
+    module: &Module,
+    layouter: &naga::proc::Layouter,
+    ep_info: &FunctionInfo,
+) -> Vec<WorkgroupBufferInfo> {
+    let mut buffers = Vec::new();
+
+    for (handle, global_var) in module.global_variables.iter() {
+        if ep_info[handle].is_empty() {
+            continue;
+        }
+        if global_var.space != AddressSpace::WorkGroup {
+            continue;
+        }
+
+        buffers.push(WorkgroupBufferInfo {
+            name: global_var.name.clone(),
+            size: layouter[global_var.ty].size,
+        });
+    }
+
+    buffers
+}
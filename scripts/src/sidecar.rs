@@ -0,0 +1,176 @@
+//! Machine-readable reflection sidecar, written alongside the compiled
+//! shader when `--reflect <path>` is passed. Modeled on the `ShaderInfo`
+//! struct Vello's compile step produces: just enough for a host to set up
+//! bind groups and scratch buffers without re-deriving any of this by hand.
+
+use naga::proc::Layouter;
+use naga::valid::ModuleInfo;
+use naga::{Module, ShaderStage};
+
+use crate::reflect::{reflect_bindings, reflect_workgroup_buffers, BindClass};
+
+pub struct ShaderInfo {
+    pub entry_points: Vec<EntryPointInfo>,
+}
+
+pub struct EntryPointInfo {
+    pub name: String,
+    pub stage: ShaderStage,
+    pub workgroup_size: [u32; 3],
+    pub bindings: Vec<BindingReflection>,
+    pub workgroup_buffers: Vec<WorkgroupBufferReflection>,
+}
+
+pub struct BindingReflection {
+    pub group: u32,
+    pub binding: u32,
+    pub bind_type: &'static str,
+    pub mutable: bool,
+    pub runtime_array_stride: Option<u32>,
+}
+
+pub struct WorkgroupBufferReflection {
+    pub name: Option<String>,
+    pub size: u32,
+}
+
+pub fn collect(module: &Module, module_info: &ModuleInfo) -> Result<ShaderInfo, String> {
+    let mut layouter = Layouter::default();
+    layouter
+        .update(&module.types, &module.constants)
+        .map_err(|e| format!("Layout error: {:?}", e))?;
+
+    let mut entry_points = Vec::new();
+    for (ep_index, entry_point) in module.entry_points.iter().enumerate() {
+        let ep_info = module_info.get_entry_point(ep_index);
+
+        let bindings = reflect_bindings(module, ep_info)
+            .into_iter()
+            .map(|binding| {
+                let (bind_type, mutable) = match binding.class {
+                    BindClass::UniformBuffer => ("uniform-buffer", false),
+                    BindClass::StorageBuffer { mutable } => {
+                        (if mutable { "storage-buffer-rw" } else { "storage-buffer-ro" }, mutable)
+                    }
+                    BindClass::Texture => ("texture", false),
+                    BindClass::Sampler { comparison } => {
+                        (if comparison { "comparison-sampler" } else { "sampler" }, false)
+                    }
+                };
+                BindingReflection {
+                    group: binding.resource_binding.group,
+                    binding: binding.resource_binding.binding,
+                    bind_type,
+                    mutable,
+                    runtime_array_stride: binding.runtime_array_stride,
+                }
+            })
+            .collect();
+
+        let workgroup_buffers = reflect_workgroup_buffers(module, &layouter, ep_info)
+            .into_iter()
+            .map(|buf| WorkgroupBufferReflection {
+                name: buf.name,
+                size: buf.size,
+            })
+            .collect();
+
+        entry_points.push(EntryPointInfo {
+            name: entry_point.name.clone(),
+            stage: entry_point.stage,
+            workgroup_size: entry_point.workgroup_size,
+            bindings,
+            workgroup_buffers,
+        });
+    }
+
+    Ok(ShaderInfo { entry_points })
+}
+
+impl ShaderInfo {
+    pub fn to_json(&self) -> String {
+        let entry_points = self
+            .entry_points
+            .iter()
+            .map(EntryPointInfo::to_json)
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{{\"entry_points\":[{}]}}", entry_points)
+    }
+}
+
+impl EntryPointInfo {
+    fn to_json(&self) -> String {
+        let stage = match self.stage {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::Fragment => "fragment",
+            ShaderStage::Compute => "compute",
+        };
+        let bindings = self
+            .bindings
+            .iter()
+            .map(BindingReflection::to_json)
+            .collect::<Vec<_>>()
+            .join(",");
+        let workgroup_buffers = self
+            .workgroup_buffers
+            .iter()
+            .map(WorkgroupBufferReflection::to_json)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{{\"name\":{},\"stage\":{},\"workgroup_size\":[{},{},{}],\"bindings\":[{}],\"workgroup_buffers\":[{}]}}",
+            json_string(&self.name),
+            json_string(stage),
+            self.workgroup_size[0],
+            self.workgroup_size[1],
+            self.workgroup_size[2],
+            bindings,
+            workgroup_buffers,
+        )
+    }
+}
+
+impl BindingReflection {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"group\":{},\"binding\":{},\"type\":{},\"mutable\":{},\"runtime_array_stride\":{}}}",
+            self.group,
+            self.binding,
+            json_string(self.bind_type),
+            self.mutable,
+            self.runtime_array_stride
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "null".to_string()),
+        )
+    }
+}
+
+impl WorkgroupBufferReflection {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"name\":{},\"size\":{}}}",
+            self.name
+                .as_deref()
+                .map(json_string)
+                .unwrap_or_else(|| "null".to_string()),
+            self.size,
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}